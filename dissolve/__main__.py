@@ -15,9 +15,78 @@
 
 def main(argv=None):
     import argparse
+    import os
+    import sys
+    import tokenize
 
     parser = argparse.ArgumentParser()
-    parser.parse_args(argv)
+    subparsers = parser.add_subparsers(dest='command')
+
+    shim_parser = subparsers.add_parser(
+        'shim-gen',
+        help='Generate a module providing replace_me without depending '
+             'on dissolve.')
+    shim_parser.add_argument(
+        '-o', '--output', default='_dissolve_shim.py',
+        help='File to write the shim module to.')
+    shim_parser.add_argument(
+        '--import-from', metavar='MODULE',
+        help='Dotted module name of the shim, used when rewriting imports.')
+    shim_parser.add_argument(
+        'paths', nargs='*',
+        help='Files whose "from dissolve import replace_me" imports should '
+             'be rewritten to use the shim.')
+
+    args = parser.parse_args(argv)
+
+    if args.command == 'shim-gen':
+        from .shim import generate_shim, rewrite_imports
+
+        if args.paths and not args.import_from:
+            parser.error('--import-from is required when rewriting imports')
+
+        with open(args.output, 'w') as f:
+            f.write(generate_shim())
+
+        ret = 0
+        for path in args.paths:
+            # The shim itself imports from dissolve; rewriting that import
+            # would make it import from itself.
+            if os.path.realpath(path) == os.path.realpath(args.output):
+                continue
+            try:
+                with open(path, 'rb') as f:
+                    encoding = tokenize.detect_encoding(f.readline)[0]
+                # Keep the file's own line endings when writing it back.
+                with open(path, encoding=encoding, newline='') as f:
+                    source = f.read()
+            except (OSError, UnicodeDecodeError, SyntaxError) as e:
+                print('%s: unable to read: %s' % (path, e), file=sys.stderr)
+                ret = 1
+                continue
+            try:
+                new_source, count, unhandled = rewrite_imports(
+                    source, args.import_from)
+            except SyntaxError as e:
+                print('%s: unable to parse: %s' % (path, e), file=sys.stderr)
+                ret = 1
+                continue
+            if count:
+                try:
+                    with open(path, 'w', encoding=encoding, newline='') as f:
+                        f.write(new_source)
+                except OSError as e:
+                    print('%s: unable to write: %s' % (path, e),
+                          file=sys.stderr)
+                    ret = 1
+                    continue
+                print('%s: rewrote %d import(s)' % (path, count))
+            for lineno in unhandled:
+                print('%s:%d: dissolve import not rewritten; '
+                      'import replace_me on its own to use the shim'
+                      % (path, lineno), file=sys.stderr)
+                ret = 1
+        return ret
 
 
 if __name__ == '__main__':
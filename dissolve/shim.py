@@ -0,0 +1,101 @@
+# Copyright (C) 2022 Jelmer Vernooij <jelmer@samba.org>
+#
+# Licensed under the Apache License, Version 2.0 (the "License");
+# you may not use this file except in compliance with the License.
+# You may obtain a copy of the License at
+#
+#    http://www.apache.org/licenses/LICENSE-2.0
+#
+# Unless required by applicable law or agreed to in writing, software
+# distributed under the License is distributed on an "AS IS" BASIS,
+# WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+# See the License for the specific language governing permissions and
+# limitations under the License.
+
+"""Generate a fallback module for libraries that don't depend on dissolve."""
+
+import ast
+import io
+
+
+SHIM_TEMPLATE = '''\
+# Generated by "dissolve shim-gen"; safe to vendor into your package.
+#
+# Uses dissolve's replace_me when dissolve is installed, and otherwise
+# falls back to a no-op decorator so dissolve is not a runtime dependency.
+
+try:
+    from dissolve import replace_me
+except ModuleNotFoundError:
+
+    def replace_me(replacement_expr, since=None):
+        def function_decorator(callable):
+            return callable
+        return function_decorator
+
+
+__all__ = ["replace_me"]
+'''
+
+
+def generate_shim():
+    """Return the source of the fallback module."""
+    return SHIM_TEMPLATE
+
+
+def _is_dissolve_import(node):
+    if isinstance(node, ast.ImportFrom):
+        return node.level == 0 and node.module is not None and (
+            node.module == 'dissolve' or node.module.startswith('dissolve.'))
+    if isinstance(node, ast.Import):
+        return any(
+            alias.name == 'dissolve' or alias.name.startswith('dissolve.')
+            for alias in node.names)
+    return False
+
+
+def rewrite_imports(source, shim_module):
+    """Point ``from dissolve import replace_me`` imports at a shim module.
+
+    Single-name imports of replace_me are rewritten, including aliased and
+    parenthesised forms. Other imports of dissolve are left alone and
+    reported, since the rewritten file would still depend on dissolve.
+
+    Args:
+      source: Python source to rewrite
+      shim_module: Dotted name of the module generated by generate_shim
+    Returns: tuple with rewritten source, number of imports rewritten and
+      list of line numbers of dissolve imports that were not rewritten
+    Raises:
+      SyntaxError: if source can not be parsed
+    """
+    # Only split on the line endings the parser recognises; str.splitlines
+    # also splits on characters like form feeds.
+    lines = io.StringIO(source, newline='').readlines()
+    rewrites = []
+    unhandled = []
+    for node in ast.walk(ast.parse(source)):
+        if not _is_dissolve_import(node):
+            continue
+        if (isinstance(node, ast.ImportFrom)
+                and node.module == 'dissolve'
+                and len(node.names) == 1
+                and node.names[0].name == 'replace_me'):
+            new = 'from %s import replace_me' % shim_module
+            if node.names[0].asname:
+                new += ' as %s' % node.names[0].asname
+            rewrites.append((node, new))
+        else:
+            unhandled.append(node.lineno)
+
+    # Apply from the bottom up so earlier positions stay valid. AST column
+    # offsets are in UTF-8 bytes.
+    for node, new in sorted(
+            rewrites, key=lambda r: (r[0].lineno, r[0].col_offset),
+            reverse=True):
+        first = lines[node.lineno - 1].encode('utf-8')
+        last = lines[node.end_lineno - 1].encode('utf-8')
+        lines[node.lineno - 1:node.end_lineno] = [
+            first[:node.col_offset].decode('utf-8') + new
+            + last[node.end_col_offset:].decode('utf-8')]
+    return ''.join(lines), len(rewrites), sorted(unhandled)
@@ -0,0 +1,222 @@
+# Copyright (C) 2022 Jelmer Vernooij <jelmer@samba.org>
+#
+# Licensed under the Apache License, Version 2.0 (the "License");
+# you may not use this file except in compliance with the License.
+# You may obtain a copy of the License at
+#
+#    http://www.apache.org/licenses/LICENSE-2.0
+#
+# Unless required by applicable law or agreed to in writing, software
+# distributed under the License is distributed on an "AS IS" BASIS,
+# WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+# See the License for the specific language governing permissions and
+# limitations under the License.
+
+import builtins
+import sys
+
+import pytest
+
+from dissolve.__main__ import main
+from dissolve.shim import generate_shim, rewrite_imports
+
+
+def test_shim_uses_dissolve():
+    namespace = {}
+    exec(generate_shim(), namespace)
+
+    import dissolve
+    assert namespace['replace_me'] is dissolve.replace_me
+
+
+def test_shim_fallback(monkeypatch):
+    real_import = builtins.__import__
+
+    def fake_import(name, *args, **kwargs):
+        if name == 'dissolve':
+            raise ModuleNotFoundError(name)
+        return real_import(name, *args, **kwargs)
+
+    monkeypatch.setattr(builtins, '__import__', fake_import)
+    namespace = {}
+    exec(generate_shim(), namespace)
+
+    def inc(x):
+        return x + 1
+
+    assert namespace['replace_me']("increment({x})", since="0.1.0")(inc) is inc
+
+
+def test_rewrite_imports():
+    source = (
+        "from dissolve import replace_me\n"
+        "try:\n"
+        "    from dissolve import replace_me\n"
+        "except ImportError:\n"
+        "    pass\n")
+    new_source, count, unhandled = rewrite_imports(
+        source, "mypkg._dissolve_shim")
+    assert count == 2
+    assert unhandled == []
+    assert new_source == (
+        "from mypkg._dissolve_shim import replace_me\n"
+        "try:\n"
+        "    from mypkg._dissolve_shim import replace_me\n"
+        "except ImportError:\n"
+        "    pass\n")
+
+
+def test_rewrite_imports_alias_and_parentheses():
+    source = (
+        "from dissolve import replace_me as rm  # deprecations\n"
+        "from dissolve import (\n"
+        "    replace_me,\n"
+        ")\n"
+        "x = 'é'; from dissolve import replace_me\n")
+    new_source, count, unhandled = rewrite_imports(
+        source, "mypkg._dissolve_shim")
+    assert count == 3
+    assert unhandled == []
+    assert new_source == (
+        "from mypkg._dissolve_shim import replace_me as rm  # deprecations\n"
+        "from mypkg._dissolve_shim import replace_me\n"
+        "x = 'é'; from mypkg._dissolve_shim import replace_me\n")
+
+
+def test_rewrite_imports_reports_unhandled():
+    source = (
+        "from dissolve import replace_me, other\n"
+        "import dissolve\n"
+        "import os, dissolve.shim\n"
+        "from dissolve.shim import generate_shim\n"
+        "from dissolver import replace_me\n")
+    new_source, count, unhandled = rewrite_imports(
+        source, "mypkg._dissolve_shim")
+    assert count == 0
+    assert unhandled == [1, 2, 3, 4]
+    assert new_source == source
+
+
+def test_rewrite_imports_form_feed():
+    source = (
+        "x = 1\n"
+        "\x0c\n"
+        "y = '\x0c'\n"
+        "from dissolve import replace_me\n")
+    new_source, count, unhandled = rewrite_imports(source, "pkg.shim")
+    assert count == 1
+    assert new_source == (
+        "x = 1\n"
+        "\x0c\n"
+        "y = '\x0c'\n"
+        "from pkg.shim import replace_me\n")
+
+
+def _make_package(path):
+    pkg = path / "mypkg"
+    pkg.mkdir()
+    (pkg / "__init__.py").write_text("")
+    (pkg / "a.py").write_text(
+        "from dissolve import replace_me\n"
+        "\n"
+        "\n"
+        "@replace_me(\"increment({x})\")\n"
+        "def inc(x):\n"
+        "    return x + 1\n")
+    return pkg
+
+
+def test_main_without_command():
+    assert main([]) is None
+
+
+def test_main_requires_import_from(tmp_path, capsys):
+    pkg = _make_package(tmp_path)
+    with pytest.raises(SystemExit):
+        main(["shim-gen", "-o", str(pkg / "_dissolve_shim.py"),
+              str(pkg / "a.py")])
+    assert "--import-from is required" in capsys.readouterr().err
+    assert not (pkg / "_dissolve_shim.py").exists()
+
+
+def test_main_shim_gen(tmp_path, capsys):
+    pkg = _make_package(tmp_path)
+    shim = pkg / "_dissolve_shim.py"
+    a = pkg / "a.py"
+    assert main(["shim-gen", "-o", str(shim),
+                 "--import-from", "mypkg._dissolve_shim", str(a)]) == 0
+    assert shim.read_text() == generate_shim()
+    assert a.read_text().startswith(
+        "from mypkg._dissolve_shim import replace_me\n")
+    assert capsys.readouterr().out == "%s: rewrote 1 import(s)\n" % a
+
+
+def test_main_errors_on_unhandled(tmp_path, capsys):
+    pkg = _make_package(tmp_path)
+    b = pkg / "b.py"
+    b.write_text("import dissolve\n")
+    assert main(["shim-gen", "-o", str(pkg / "_dissolve_shim.py"),
+                 "--import-from", "mypkg._dissolve_shim", str(b)]) == 1
+    assert b.read_text() == "import dissolve\n"
+    assert "%s:1: dissolve import not rewritten" % b in (
+        capsys.readouterr().err)
+
+
+def test_main_shim_gen_twice(tmp_path, monkeypatch):
+    pkg = _make_package(tmp_path)
+    shim = pkg / "_dissolve_shim.py"
+    monkeypatch.chdir(tmp_path)
+    for i in range(2):
+        # Like a shell glob, the second run includes the generated shim.
+        paths = sorted(str(p.relative_to(tmp_path)) for p in pkg.glob("*.py"))
+        assert main(["shim-gen", "-o", str(shim),
+                     "--import-from", "mypkg._dissolve_shim"] + paths) == 0
+    assert shim.read_text() == generate_shim()
+
+    monkeypatch.syspath_prepend(str(tmp_path))
+    namespace = {}
+    exec("from mypkg.a import inc", namespace)
+    try:
+        with pytest.deprecated_call():
+            assert namespace["inc"](x=2) == 3
+    finally:
+        for name in ["mypkg", "mypkg.a", "mypkg._dissolve_shim"]:
+            sys.modules.pop(name, None)
+
+
+def test_main_preserves_crlf(tmp_path):
+    a = tmp_path / "a.py"
+    a.write_bytes(b"x = 1\r\nfrom dissolve import replace_me\r\ny = 2\r\n")
+    assert main(["shim-gen", "-o", str(tmp_path / "s.py"),
+                 "--import-from", "pkg.s", str(a)]) == 0
+    assert a.read_bytes() == (
+        b"x = 1\r\nfrom pkg.s import replace_me\r\ny = 2\r\n")
+
+
+def test_main_coding_declaration(tmp_path):
+    a = tmp_path / "a.py"
+    a.write_bytes(
+        b"# -*- coding: latin-1 -*-\n"
+        b"name = '\xe9'\n"
+        b"from dissolve import replace_me\n")
+    assert main(["shim-gen", "-o", str(tmp_path / "s.py"),
+                 "--import-from", "pkg.s", str(a)]) == 0
+    assert a.read_bytes() == (
+        b"# -*- coding: latin-1 -*-\n"
+        b"name = '\xe9'\n"
+        b"from pkg.s import replace_me\n")
+
+
+def test_main_unreadable_paths(tmp_path, capsys):
+    bad = tmp_path / "bad.py"
+    bad.write_bytes(b"name = '\xe9'\n")
+    missing = tmp_path / "missing.py"
+    a = tmp_path / "a.py"
+    a.write_text("from dissolve import replace_me\n")
+    assert main(["shim-gen", "-o", str(tmp_path / "s.py"),
+                 "--import-from", "pkg.s",
+                 str(bad), str(missing), str(a)]) == 1
+    err = capsys.readouterr().err
+    assert "%s: unable to read: " % bad in err
+    assert "%s: unable to read: " % missing in err
+    assert a.read_text() == "from pkg.s import replace_me\n"